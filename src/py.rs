@@ -13,6 +13,64 @@ use rustc_hash::FxHashMap as HashMap;
 
 use crate::{byte_pair_encode, CoreBPE, Rank};
 
+// Self-describing binary model format. Layout is a fixed magic + version byte,
+// then three length-prefixed sections: the ordinary encoder, the special-token
+// encoder, and the regex pattern. Counts are u64 big-endian; individual lengths
+// and ranks are LEB128 varints so that the common small values stay compact.
+const MODEL_MAGIC: &[u8; 5] = b"TKBPE";
+const MODEL_VERSION: u8 = 1;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> PyResult<u64> {
+    let mut result: u64 = 0;
+    let mut shift = 0u32;
+    loop {
+        let byte = *data.get(*pos).ok_or_else(|| {
+            PyErr::new::<exceptions::PyValueError, _>("truncated model data (varint)")
+        })?;
+        *pos += 1;
+        result |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(PyErr::new::<exceptions::PyValueError, _>(
+                "malformed model data (varint overflow)",
+            ));
+        }
+    }
+    Ok(result)
+}
+
+fn read_bytes<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> PyResult<&'a [u8]> {
+    let end = pos
+        .checked_add(len)
+        .filter(|&end| end <= data.len())
+        .ok_or_else(|| PyErr::new::<exceptions::PyValueError, _>("truncated model data"))?;
+    let slice = &data[*pos..end];
+    *pos = end;
+    Ok(slice)
+}
+
+fn read_u64(data: &[u8], pos: &mut usize) -> PyResult<u64> {
+    let slice = read_bytes(data, pos, 8)?;
+    Ok(u64::from_be_bytes(slice.try_into().unwrap()))
+}
+
 #[pymethods]
 impl CoreBPE {
     #[new]
@@ -29,6 +87,104 @@ impl CoreBPE {
         .map_err(|e| PyErr::new::<exceptions::PyValueError, _>(e.to_string()))
     }
 
+    // ====================
+    // Serialization
+    // ====================
+
+    /// Serialize the full tokenizer state (ordinary encoder, special-token
+    /// encoder, and regex pattern) into one compact, self-describing blob that
+    /// can be written to disk and loaded with `from_bytes` without rebuilding a
+    /// giant Python dict on every process start.
+    #[pyo3(name = "dump_bytes")]
+    fn py_dump_bytes(&self, py: Python) -> Py<PyBytes> {
+        let data = py.allow_threads(|| {
+            let mut out = Vec::new();
+            out.extend_from_slice(MODEL_MAGIC);
+            out.push(MODEL_VERSION);
+
+            out.extend_from_slice(&(self.encoder.len() as u64).to_be_bytes());
+            for (bytes, rank) in &self.encoder {
+                write_varint(&mut out, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+                write_varint(&mut out, u64::from(*rank));
+            }
+
+            out.extend_from_slice(&(self.special_tokens_encoder.len() as u64).to_be_bytes());
+            for (name, rank) in &self.special_tokens_encoder {
+                write_varint(&mut out, name.len() as u64);
+                out.extend_from_slice(name.as_bytes());
+                write_varint(&mut out, u64::from(*rank));
+            }
+
+            let pattern = self.regex_tls[0].as_str();
+            write_varint(&mut out, pattern.len() as u64);
+            out.extend_from_slice(pattern.as_bytes());
+
+            out
+        });
+        PyBytes::new_bound(py, &data).into()
+    }
+
+    /// Reconstruct a `CoreBPE` from a blob produced by `dump_bytes`. The decoder
+    /// and `sorted_token_bytes` are rebuilt by delegating to `new_internal`, so
+    /// the result is indistinguishable from one built via the normal
+    /// constructor. A wrong magic or version is rejected with `ValueError`.
+    #[staticmethod]
+    #[pyo3(name = "from_bytes")]
+    fn py_from_bytes(data: &[u8]) -> PyResult<Self> {
+        let mut pos = 0usize;
+
+        if read_bytes(data, &mut pos, MODEL_MAGIC.len())? != MODEL_MAGIC {
+            return Err(PyErr::new::<exceptions::PyValueError, _>(
+                "not a tiktoken model blob (bad magic)",
+            ));
+        }
+        let version = *data
+            .get(pos)
+            .ok_or_else(|| PyErr::new::<exceptions::PyValueError, _>("truncated model data"))?;
+        pos += 1;
+        if version != MODEL_VERSION {
+            return Err(PyErr::new::<exceptions::PyValueError, _>(format!(
+                "unsupported model version {} (expected {})",
+                version, MODEL_VERSION
+            )));
+        }
+
+        let encoder_len = read_u64(data, &mut pos)? as usize;
+        let mut encoder: HashMap<Vec<u8>, Rank> =
+            HashMap::with_capacity_and_hasher(encoder_len, Default::default());
+        for _ in 0..encoder_len {
+            let len = read_varint(data, &mut pos)? as usize;
+            let bytes = read_bytes(data, &mut pos, len)?.to_vec();
+            let rank = Rank::try_from(read_varint(data, &mut pos)?).map_err(|_| {
+                PyErr::new::<exceptions::PyValueError, _>("rank out of range")
+            })?;
+            encoder.insert(bytes, rank);
+        }
+
+        let special_len = read_u64(data, &mut pos)? as usize;
+        let mut special_tokens_encoder: HashMap<String, Rank> =
+            HashMap::with_capacity_and_hasher(special_len, Default::default());
+        for _ in 0..special_len {
+            let len = read_varint(data, &mut pos)? as usize;
+            let name = std::str::from_utf8(read_bytes(data, &mut pos, len)?)
+                .map_err(|e| PyErr::new::<exceptions::PyValueError, _>(e.to_string()))?
+                .to_owned();
+            let rank = Rank::try_from(read_varint(data, &mut pos)?).map_err(|_| {
+                PyErr::new::<exceptions::PyValueError, _>("rank out of range")
+            })?;
+            special_tokens_encoder.insert(name, rank);
+        }
+
+        let pattern_len = read_varint(data, &mut pos)? as usize;
+        let pattern = std::str::from_utf8(read_bytes(data, &mut pos, pattern_len)?)
+            .map_err(|e| PyErr::new::<exceptions::PyValueError, _>(e.to_string()))?;
+
+        Self::new_internal(encoder, special_tokens_encoder, pattern)
+            .map_err(|e| PyErr::new::<exceptions::PyValueError, _>(e.to_string()))
+    }
+
+
     // ====================
     // Encoding
     // ====================
@@ -416,6 +572,39 @@ impl CoreBPE {
             .map(|x| PyBytes::new_bound(py, x).into())
             .collect()
     }
+
+    /// Return the ranks of every token whose bytes begin with `prefix`. Because
+    /// `sorted_token_bytes` is kept in byte order, the matching tokens form a
+    /// contiguous run starting at the first entry not ordered before `prefix`,
+    /// so this answers in `O(log n + k)` rather than scanning the whole table.
+    /// The motivating use is constrained decoding, where a sampler needs the
+    /// legal continuations of a partial byte string to build a logit mask.
+    fn tokens_with_prefix(&self, prefix: &[u8]) -> Vec<Rank> {
+        let start = self
+            .sorted_token_bytes
+            .partition_point(|bytes| bytes.as_slice() < prefix);
+        self.sorted_token_bytes[start..]
+            .iter()
+            .take_while(|bytes| bytes.starts_with(prefix))
+            .map(|bytes| self.encoder[bytes.as_slice()])
+            .collect()
+    }
+
+    /// Return the ranks of every token whose bytes fall within the half-open
+    /// byte range `[lo, hi)`, found by binary-searching the sorted token bytes.
+    fn token_bytes_in_range(&self, lo: &[u8], hi: &[u8]) -> Vec<Rank> {
+        let start = self
+            .sorted_token_bytes
+            .partition_point(|bytes| bytes.as_slice() < lo);
+        let end = self
+            .sorted_token_bytes
+            .partition_point(|bytes| bytes.as_slice() < hi)
+            .max(start);
+        self.sorted_token_bytes[start..end]
+            .iter()
+            .map(|bytes| self.encoder[bytes.as_slice()])
+            .collect()
+    }
 }
 
 #[pyclass]
@@ -475,8 +664,62 @@ impl TiktokenBuffer {
     }
 }
 
+/// Stateful decoder for incrementally streaming model output. Tokens are
+/// pushed as they are generated; each `push` emits the longest prefix of the
+/// accumulated bytes that is valid UTF-8 and buffers any trailing bytes that
+/// fall in the middle of a multi-byte codepoint until the next call, so a
+/// split codepoint never produces a decode error mid-stream.
+#[pyclass]
+struct StreamDecoder {
+    bpe: Py<CoreBPE>,
+    pending: Vec<u8>,
+}
+
+#[pymethods]
+impl StreamDecoder {
+    #[new]
+    fn new(bpe: Py<CoreBPE>) -> Self {
+        StreamDecoder {
+            bpe,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Decode `tokens`, append the bytes to the pending buffer, and return the
+    /// longest valid-UTF-8 prefix available so far. Returns an empty string
+    /// when every new byte is still part of an incomplete codepoint.
+    fn push(&mut self, py: Python, tokens: Vec<Rank>) -> PyResult<String> {
+        let bytes = {
+            let bpe = self.bpe.borrow(py);
+            // A per-step decode is tiny, so we keep the GIL rather than release
+            // it: `PyRef` is `!Ungil` and cannot cross `allow_threads`.
+            bpe.decode_bytes(&tokens)
+                .map_err(|e| pyo3::exceptions::PyKeyError::new_err(format!("{}", e)))?
+        };
+        self.pending.extend_from_slice(&bytes);
+
+        let valid_up_to = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        let ready: Vec<u8> = self.pending.drain(..valid_up_to).collect();
+        // Safety: `ready` is exactly the prefix `from_utf8` reported as valid.
+        Ok(unsafe { String::from_utf8_unchecked(ready) })
+    }
+
+    /// Return any bytes still buffered at end-of-stream. These are the bytes of
+    /// a truncated trailing codepoint or, if the stream held an invalid UTF-8
+    /// sequence, everything from that sequence on; they are returned raw so
+    /// the caller can decide how to handle them.
+    fn flush(&mut self, py: Python) -> Py<PyBytes> {
+        let remaining = std::mem::take(&mut self.pending);
+        PyBytes::new_bound(py, &remaining).into()
+    }
+}
+
 #[pymodule]
 fn _tiktoken(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
     m.add_class::<CoreBPE>()?;
+    m.add_class::<StreamDecoder>()?;
     Ok(())
 }